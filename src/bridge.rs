@@ -0,0 +1,289 @@
+//! TCP front-end for a Pico attached over serial: `Serve` accepts remote
+//! clients and lets each drive the same `Load`/`Kill`/`Relaunch`/`List`
+//! commands picosh takes locally, with ELF bytes streamed over the socket
+//! instead of read from a local file. The Pico's other serial output —
+//! firmware prints and log lines that aren't a reply to any client's
+//! command — is mirrored to every connected client as it arrives.
+//!
+//! Wire protocol is one line per command, newline-terminated:
+//!   `LOAD <symbol> <identifier> <elf byte count>\n` followed by the raw ELF
+//!   bytes, `KILL <identifier>\n`, `RELAUNCH <identifier>\n`, `LIST\n`.
+//! Each command is tagged with a request id the same way the local CLI path
+//! tags it (`protocol::tag_with_request_id`) and the matching reply —
+//! the same aligned table the CLI prints for `LIST`, an `OK <message>` or
+//! `ERR <status> (<message>)` line otherwise — is written back to the
+//! client, instead of mirroring raw serial bytes and leaving the client to
+//! make sense of them.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use serialport::SerialPort;
+
+use crate::logviewer::LogRecord;
+use crate::{
+    framing, handle_kill_cmd, handle_list_cmd, handle_load_cmd, handle_relaunch_cmd, logviewer,
+    protocol, tasktable,
+};
+
+/// How long a connected client may sit idle before being dropped.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(300);
+/// How long to wait for the Pico to answer a forwarded command.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Largest ELF a remote `LOAD` may upload. Well past anything that fits in
+/// a Pico's flash, but small enough that a bogus byte count can't exhaust
+/// host memory before it's even read off the wire.
+const MAX_ELF_LEN: usize = 16 * 1024 * 1024;
+
+/// The Pico only ever has one command in flight at a time, so every
+/// client's "send a tagged command, wait for its matching reply" cycle is
+/// serialized through this lock — otherwise one client's `recv` loop could
+/// discard a reply meant for another client waiting on the same channel.
+struct Sequencer {
+    serial: Arc<Mutex<Box<dyn SerialPort + 'static>>>,
+    replies: Mutex<Receiver<protocol::Reply>>,
+    tables: Mutex<Receiver<(u8, Vec<tasktable::TaskInfo>)>>,
+}
+
+/// Every connected client's writer half, so firmware output that isn't a
+/// reply to anyone's command can be mirrored to all of them.
+struct Broadcast {
+    clients: Mutex<Vec<TcpStream>>,
+}
+
+impl Broadcast {
+    fn send_all(&self, bytes: &[u8]) {
+        let mut clients = self.clients.lock().unwrap();
+        clients.retain_mut(|client| client.write_all(bytes).is_ok());
+    }
+}
+
+/// Accept TCP clients on `bind`, forwarding their commands to `serial` and
+/// writing back the matching reply. Each client is handled on its own
+/// thread, with panics caught at the boundary, so a malformed `LOAD`/`KILL`
+/// from one client (bad ELF, missing symbol, ...) can't take down the
+/// bridge for every other client or drop the serial connection.
+///
+/// `log_rx` and `passthrough_rx` are the same decoded-frame streams the
+/// default run loop prints to stdout and `Log` renders — here they're
+/// mirrored to every connected client instead, so a developer can flash and
+/// monitor a lab Pico over the network.
+pub fn serve(
+    bind: SocketAddr,
+    serial: Arc<Mutex<Box<dyn SerialPort + 'static>>>,
+    reply_rx: Receiver<protocol::Reply>,
+    table_rx: Receiver<(u8, Vec<tasktable::TaskInfo>)>,
+    log_rx: Receiver<LogRecord>,
+    passthrough_rx: Receiver<Vec<u8>>,
+) -> io::Result<()> {
+    let listener = TcpListener::bind(bind)?;
+    println!("[PICOSH] serving on {bind}, waiting for clients");
+
+    let sequencer = Arc::new(Sequencer {
+        serial,
+        replies: Mutex::new(reply_rx),
+        tables: Mutex::new(table_rx),
+    });
+
+    let broadcast = Arc::new(Broadcast {
+        clients: Mutex::new(Vec::new()),
+    });
+
+    {
+        let broadcast = Arc::clone(&broadcast);
+        thread::spawn(move || {
+            for payload in passthrough_rx {
+                broadcast.send_all(&payload);
+            }
+        });
+    }
+    {
+        let broadcast = Arc::clone(&broadcast);
+        thread::spawn(move || {
+            for record in log_rx {
+                broadcast.send_all(format!("{}\n", logviewer::render(&record)).as_bytes());
+            }
+        });
+    }
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let peer = stream.peer_addr().ok();
+                println!("[PICOSH] client connected: {peer:?}");
+
+                if let Ok(mirror) = stream.try_clone() {
+                    broadcast.clients.lock().unwrap().push(mirror);
+                }
+                let closer = stream.try_clone().ok();
+
+                let sequencer = Arc::clone(&sequencer);
+                thread::spawn(move || {
+                    let result =
+                        panic::catch_unwind(AssertUnwindSafe(|| handle_client(stream, sequencer)));
+                    match result {
+                        Ok(Ok(())) => {}
+                        Ok(Err(err)) => {
+                            eprintln!("[PICOSH] client session ended with error: {err}");
+                        }
+                        Err(_) => {
+                            eprintln!("[PICOSH] client session panicked, dropping that client only");
+                        }
+                    }
+                    // Close the socket itself, so the broadcast mirror's next
+                    // write fails and removes it instead of keeping it open.
+                    if let Some(closer) = closer {
+                        let _ = closer.shutdown(Shutdown::Both);
+                    }
+                    println!("[PICOSH] client disconnected: {peer:?}");
+                });
+            }
+            Err(err) => eprintln!("[PICOSH] failed to accept client: {err}"),
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_client(stream: TcpStream, sequencer: Arc<Sequencer>) -> io::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+
+    let mut writer_stream = stream.try_clone()?;
+    let mut lines = BufReader::new(stream);
+
+    loop {
+        let mut line = String::new();
+        if lines.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+
+        let is_list = line.split_whitespace().next() == Some("LIST");
+
+        let payload = match parse_command(line.trim_end(), &mut lines)? {
+            Some(payload) => payload,
+            None => {
+                writer_stream.write_all(b"ERR unknown command\n")?;
+                continue;
+            }
+        };
+
+        let (request_id, tagged) = protocol::tag_with_request_id(&payload);
+        let framed = framing::frame(&tagged);
+
+        // Hold both receivers for the full send-then-await cycle so no
+        // other client's reply can be mistaken for, or discard, this one.
+        let replies = sequencer.replies.lock().unwrap();
+        let tables = sequencer.tables.lock().unwrap();
+
+        {
+            let mut writer_handle = sequencer.serial.lock().unwrap();
+            writer_handle.write_all(&framed).unwrap();
+            writer_handle.flush().unwrap();
+        }
+
+        if is_list {
+            match tasktable::await_task_table(&tables, request_id, REPLY_TIMEOUT) {
+                Some(tasks) => writer_stream.write_all(tasktable::render_table(&tasks).as_bytes())?,
+                None => writer_stream.write_all(b"ERR timed out waiting for the task table\n")?,
+            }
+        } else {
+            match protocol::send_and_await(&replies, request_id, REPLY_TIMEOUT) {
+                Some(reply) if reply.status.is_ok() => {
+                    writeln!(writer_stream, "OK {}", reply.message)?
+                }
+                Some(reply) => writeln!(writer_stream, "ERR {} ({})", reply.status, reply.message)?,
+                None => writer_stream.write_all(b"ERR timed out waiting for a reply\n")?,
+            }
+        }
+
+        drop(tables);
+        drop(replies);
+    }
+}
+
+/// Reject anything but a plain `[A-Za-z0-9_-]` identifier before it's
+/// spliced into a filesystem path, so a remote client can't smuggle `/` or
+/// `..` segments into the bridge's tmp-file name. Truncated to 8 bytes,
+/// same as the wire encoding.
+fn sanitize_identifier(identifier: &str) -> Option<String> {
+    if identifier.is_empty()
+        || !identifier
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return None;
+    }
+    Some(identifier.chars().take(8).collect())
+}
+
+/// Parse one command line, reading any trailing raw bytes (the ELF payload
+/// for `LOAD`) straight off `lines`, and build the same wire payload the
+/// local CLI path would.
+fn parse_command(line: &str, lines: &mut BufReader<TcpStream>) -> io::Result<Option<Vec<u8>>> {
+    let mut parts = line.split_whitespace();
+
+    let payload = match parts.next() {
+        Some("LOAD") => {
+            let symbol = parts.next().unwrap_or_default().to_string();
+            let identifier = match parts.next().map(sanitize_identifier) {
+                Some(Some(identifier)) => identifier,
+                _ => return Ok(None),
+            };
+            let elf_len: usize = match parts.next().unwrap_or("0").parse() {
+                Ok(len) if len <= MAX_ELF_LEN => len,
+                _ => {
+                    return Ok(None);
+                }
+            };
+
+            let mut elf_bytes = vec![0u8; elf_len];
+            lines.read_exact(&mut elf_bytes)?;
+
+            let tmp_path = std::env::temp_dir().join(format!("picosh-bridge-{identifier}.elf"));
+            std::fs::write(&tmp_path, &elf_bytes)?;
+
+            handle_load_cmd(tmp_path.to_string_lossy().into_owned(), symbol, identifier)
+        }
+        Some("KILL") => handle_kill_cmd(parts.next().unwrap_or_default().to_string()),
+        Some("RELAUNCH") => handle_relaunch_cmd(parts.next().unwrap_or_default().to_string()),
+        Some("LIST") => handle_list_cmd(),
+        _ => None,
+    };
+
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_empty_identifier() {
+        assert_eq!(sanitize_identifier(""), None);
+    }
+
+    #[test]
+    fn rejects_dot_dot() {
+        assert_eq!(sanitize_identifier(".."), None);
+    }
+
+    #[test]
+    fn rejects_embedded_slash() {
+        assert_eq!(sanitize_identifier("a/b"), None);
+    }
+
+    #[test]
+    fn accepts_normal_identifier() {
+        assert_eq!(sanitize_identifier("task-1"), Some("task-1".to_string()));
+    }
+
+    #[test]
+    fn truncates_over_eight_chars() {
+        assert_eq!(sanitize_identifier("abcdefghij"), Some("abcdefgh".to_string()));
+    }
+}
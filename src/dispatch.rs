@@ -0,0 +1,88 @@
+//! Shared serial frame reader.
+//!
+//! The default run loop, `Serve`, and `Log` all need to read framed
+//! payloads off the same serial port and tell an RPC call, a task table, a
+//! command reply, and a log record apart. Previously each front-end did
+//! this with its own hand-rolled read loop — which meant an RPC call
+//! arriving while `Log` had the port open would never reach
+//! `rpc::handle_call`, since `Log`'s loop only ever tried to decode log
+//! records. This module is the one place that reads the port now, so RPC
+//! dispatch is shared no matter which front-end is running.
+
+use std::io::Read;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use serialport::SerialPort;
+
+use crate::logviewer::LogRecord;
+use crate::{framing, logviewer, protocol, rpc, tasktable};
+
+/// Where a decoded frame goes, keyed by which parser recognised it. A
+/// front-end that doesn't care about one of these just drops its receiver;
+/// sends on a channel with no receiver are silently ignored.
+pub struct Channels {
+    pub replies: Sender<protocol::Reply>,
+    pub tables: Sender<(u8, Vec<tasktable::TaskInfo>)>,
+    pub logs: Sender<LogRecord>,
+    pub passthrough: Sender<Vec<u8>>,
+}
+
+/// Spawn the thread that reads framed payloads off `serial` for the life of
+/// the process, dispatching each one to `channels`. RPC calls are handled
+/// (and replied to) inline rather than forwarded, the same as before this
+/// module existed.
+pub fn spawn_reader(
+    serial: Arc<Mutex<Box<dyn SerialPort + 'static>>>,
+    rpc_registry: rpc::Registry,
+    channels: Channels,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut frame_buf: Vec<u8> = Vec::new();
+
+        loop {
+            let handler_result = serial.lock();
+
+            if let Ok(mut handler) = handler_result {
+                let mut read_buf = [0u8; 1];
+                if handler.read_exact(&mut read_buf).is_err() {
+                    continue;
+                }
+                drop(handler);
+
+                let byte = read_buf[0];
+                if byte == 0x00 {
+                    match framing::deframe(&frame_buf) {
+                        Some(payload) => dispatch(&payload, &rpc_registry, &serial, &channels),
+                        None => {
+                            println!("[PICOSH] dropping frame that failed CRC-16 or COBS decode");
+                        }
+                    }
+                    frame_buf.clear();
+                } else {
+                    frame_buf.push(byte);
+                }
+            }
+        }
+    })
+}
+
+fn dispatch(
+    payload: &[u8],
+    rpc_registry: &rpc::Registry,
+    serial: &Arc<Mutex<Box<dyn SerialPort + 'static>>>,
+    channels: &Channels,
+) {
+    if let Some(call) = rpc::RpcCall::decode(payload) {
+        rpc::handle_call(&call, rpc_registry, serial);
+    } else if let Some((request_id, tasks)) = tasktable::decode(payload) {
+        _ = channels.tables.send((request_id, tasks));
+    } else if let Some(reply) = protocol::Reply::decode(payload) {
+        _ = channels.replies.send(reply);
+    } else if let Some(record) = logviewer::decode(payload) {
+        _ = channels.logs.send(record);
+    } else {
+        _ = channels.passthrough.send(payload.to_vec());
+    }
+}
@@ -0,0 +1,206 @@
+//! COBS + CRC-16/CCITT framing for the serial link.
+//!
+//! Every payload written to the Pico is terminated with a `0x00` delimiter on
+//! the wire, so the payload itself must never contain a zero byte. COBS
+//! (Consistent Overhead Byte Stuffing) rewrites the payload so that's true:
+//! it walks the input in runs of up to 254 non-zero bytes, and before each
+//! run emits a length code (run length + 1, so it's never zero) covering the
+//! bytes up to the next zero byte or run boundary. Decoding reverses this by
+//! reading a length code and copying that many bytes, inserting a zero byte
+//! wherever a run was cut short by one in the original data.
+//!
+//! A CRC-16/CCITT is appended to the payload before encoding so a corrupted
+//! frame can be detected and dropped instead of silently desyncing the
+//! receiver.
+
+const MAX_RUN: usize = 254;
+
+/// CRC-16/CCITT-FALSE: poly 0x1021, init 0xFFFF, no reflection, no xorout.
+pub fn crc16_ccitt(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            if crc & 0x8000 != 0 {
+                crc = (crc << 1) ^ 0x1021;
+            } else {
+                crc <<= 1;
+            }
+        }
+    }
+    crc
+}
+
+/// COBS-encode `data`. The result never contains a `0x00` byte.
+pub fn cobs_encode(data: &[u8]) -> Vec<u8> {
+    let mut out: Vec<u8> = Vec::with_capacity(data.len() + data.len() / MAX_RUN + 2);
+    let mut code_index = out.len();
+    out.push(0); // placeholder, patched below
+    let mut code: u8 = 1;
+
+    for &byte in data {
+        if byte == 0 {
+            out[code_index] = code;
+            code_index = out.len();
+            out.push(0);
+            code = 1;
+        } else {
+            out.push(byte);
+            code += 1;
+            if code as usize == MAX_RUN + 1 {
+                out[code_index] = code;
+                code_index = out.len();
+                out.push(0);
+                code = 1;
+            }
+        }
+    }
+
+    out[code_index] = code;
+    out
+}
+
+/// Decode a COBS-encoded buffer (without the trailing `0x00` delimiter).
+/// Returns `None` if the buffer is malformed.
+pub fn cobs_decode(data: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut i = 0;
+
+    while i < data.len() {
+        let code = data[i] as usize;
+        if code == 0 || i + code > data.len() + 1 {
+            return None;
+        }
+        i += 1;
+        let run_end = i + code - 1;
+        out.extend_from_slice(data.get(i..run_end)?);
+        i = run_end;
+
+        if code != MAX_RUN + 1 && i < data.len() {
+            out.push(0);
+        }
+    }
+
+    Some(out)
+}
+
+/// Append a CRC-16/CCITT to `payload`, COBS-encode it, and terminate the
+/// result with a `0x00` delimiter ready to write straight to the serial port.
+pub fn frame(payload: &[u8]) -> Vec<u8> {
+    let mut with_crc = Vec::with_capacity(payload.len() + 2);
+    with_crc.extend_from_slice(payload);
+    with_crc.extend_from_slice(&crc16_ccitt(payload).to_be_bytes());
+
+    let mut out = cobs_encode(&with_crc);
+    out.push(0);
+    out
+}
+
+/// Decode a single COBS frame (delimiter already stripped) and verify its
+/// trailing CRC-16. Returns the original payload, or `None` if the frame is
+/// malformed or fails the integrity check.
+pub fn deframe(frame: &[u8]) -> Option<Vec<u8>> {
+    let decoded = cobs_decode(frame)?;
+    if decoded.len() < 2 {
+        return None;
+    }
+
+    let (payload, crc_bytes) = decoded.split_at(decoded.len() - 2);
+    let expected = u16::from_be_bytes([crc_bytes[0], crc_bytes[1]]);
+    if crc16_ccitt(payload) != expected {
+        return None;
+    }
+
+    Some(payload.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc16_ccitt_check_value() {
+        // Standard CRC-16/CCITT-FALSE check value for the ASCII string "123456789".
+        assert_eq!(crc16_ccitt(b"123456789"), 0x29B1);
+    }
+
+    #[test]
+    fn cobs_round_trip_empty() {
+        let encoded = cobs_encode(&[]);
+        assert_eq!(cobs_decode(&encoded).unwrap(), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn cobs_round_trip_no_zeros() {
+        let data = b"hello world";
+        let encoded = cobs_encode(data);
+        assert!(!encoded.contains(&0));
+        assert_eq!(cobs_decode(&encoded).unwrap(), data);
+    }
+
+    #[test]
+    fn cobs_round_trip_with_zeros() {
+        let data = [0x01, 0x00, 0x02, 0x00, 0x00, 0x03];
+        let encoded = cobs_encode(&data);
+        assert!(!encoded.contains(&0));
+        assert_eq!(cobs_decode(&encoded).unwrap(), data);
+    }
+
+    #[test]
+    fn cobs_round_trip_long_run() {
+        // Exercises the run-length wraparound at MAX_RUN (254) non-zero bytes.
+        let data = vec![0xAB; 600];
+        let encoded = cobs_encode(&data);
+        assert!(!encoded.contains(&0));
+        assert_eq!(cobs_decode(&encoded).unwrap(), data);
+    }
+
+    #[test]
+    fn cobs_reference_vector() {
+        // From the canonical COBS paper: 0x00 encodes to [0x01, 0x01].
+        assert_eq!(cobs_encode(&[0x00]), vec![0x01, 0x01]);
+        // 0x11 0x22 0x00 0x33 encodes to [0x03, 0x11, 0x22, 0x02, 0x33].
+        assert_eq!(
+            cobs_encode(&[0x11, 0x22, 0x00, 0x33]),
+            vec![0x03, 0x11, 0x22, 0x02, 0x33]
+        );
+    }
+
+    #[test]
+    fn cobs_decode_rejects_truncated_run() {
+        // Code byte claims a run longer than the remaining buffer.
+        assert!(cobs_decode(&[0x05, 0x01, 0x02]).is_none());
+    }
+
+    #[test]
+    fn cobs_decode_rejects_zero_code() {
+        assert!(cobs_decode(&[0x00, 0x01]).is_none());
+    }
+
+    #[test]
+    fn frame_deframe_round_trip() {
+        let payload = b"LISTPROG request payload";
+        let framed = frame(payload);
+        assert_eq!(*framed.last().unwrap(), 0x00);
+
+        // `deframe` expects the delimiter already stripped, matching how the
+        // shared reader thread buffers bytes up to (but not including) the 0x00.
+        let body = &framed[..framed.len() - 1];
+        assert_eq!(deframe(body).unwrap(), payload);
+    }
+
+    #[test]
+    fn deframe_rejects_corrupted_crc() {
+        let payload = b"hello";
+        let framed = frame(payload);
+        let mut body = framed[..framed.len() - 1].to_vec();
+        let last = body.len() - 1;
+        body[last] ^= 0xFF;
+        assert!(deframe(&body).is_none());
+    }
+
+    #[test]
+    fn deframe_rejects_malformed_cobs() {
+        assert!(deframe(&[0x05, 0x01, 0x02]).is_none());
+    }
+}
@@ -0,0 +1,169 @@
+//! Level-aware log viewer for the `Log` command.
+//!
+//! A log record frame is `[LOG_RECORD_MAGIC][identifier: 8 bytes][level: u8]
+//! [tick: u64 LE][message bytes]`, produced by a `log`-crate backend on the
+//! Pico firmware. Each record is rendered with a host timestamp, a
+//! colorized level, and the source task's identifier, instead of the raw
+//! byte stream `Log` used to dump straight to stdout.
+//!
+//! Records are decoded by the shared [`crate::dispatch`] reader thread and
+//! handed to `run` over a channel, rather than `Log` reading the serial
+//! port itself — otherwise an RPC call (chunk0-4) arriving while `Log` is
+//! open would never reach `rpc::handle_call` and the firmware-side caller
+//! would hang with no reply.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub const LOG_RECORD_MAGIC: &[u8] = b"LOGLINE;";
+
+/// How long `--follow`-less sessions keep capturing before exiting, giving
+/// one task's backlog a chance to drain instead of printing nothing.
+const SNAPSHOT_WINDOW: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    fn from_byte(byte: u8) -> Option<Level> {
+        match byte {
+            0 => Some(Level::Trace),
+            1 => Some(Level::Debug),
+            2 => Some(Level::Info),
+            3 => Some(Level::Warn),
+            4 => Some(Level::Error),
+            _ => None,
+        }
+    }
+
+    /// ANSI-colorized label, e.g. `\x1b[33mWARN\x1b[0m`.
+    fn colorized(&self) -> String {
+        let (color, label) = match self {
+            Level::Trace => ("90", "TRACE"),
+            Level::Debug => ("36", "DEBUG"),
+            Level::Info => ("32", "INFO"),
+            Level::Warn => ("33", "WARN"),
+            Level::Error => ("31", "ERROR"),
+        };
+        format!("\x1b[{color}m{label}\x1b[0m")
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum LevelArg {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl From<LevelArg> for Level {
+    fn from(value: LevelArg) -> Self {
+        match value {
+            LevelArg::Trace => Level::Trace,
+            LevelArg::Debug => Level::Debug,
+            LevelArg::Info => Level::Info,
+            LevelArg::Warn => Level::Warn,
+            LevelArg::Error => Level::Error,
+        }
+    }
+}
+
+/// A single decoded log record.
+pub struct LogRecord {
+    identifier: String,
+    level: Level,
+    tick: u64,
+    message: String,
+}
+
+/// Parse `payload` as a log record frame. Returns `None` if it doesn't
+/// start with `LOG_RECORD_MAGIC` or is malformed. Called from
+/// `dispatch::spawn_reader`'s shared reader thread.
+pub(crate) fn decode(payload: &[u8]) -> Option<LogRecord> {
+    let rest = payload.strip_prefix(LOG_RECORD_MAGIC)?;
+    if rest.len() < 8 + 1 + 8 {
+        return None;
+    }
+
+    let identifier = String::from_utf8_lossy(&rest[0..8]).trim_end().to_string();
+    let level = Level::from_byte(rest[8])?;
+    let tick = u64::from_le_bytes(rest[9..17].try_into().unwrap());
+    let message = String::from_utf8_lossy(&rest[17..]).into_owned();
+
+    Some(LogRecord {
+        identifier,
+        level,
+        tick,
+        message,
+    })
+}
+
+/// Render `record` with a host timestamp, colorized level, and source
+/// identifier. Used by `run`'s own stdout output and, via `bridge`, to
+/// mirror log lines to connected `Serve` clients.
+pub(crate) fn render(record: &LogRecord) -> String {
+    let host_secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    format!(
+        "[{host_secs}] {} {:<8} (tick {}) {}",
+        record.level.colorized(),
+        record.identifier,
+        record.tick,
+        record.message
+    )
+}
+
+/// Consume log records decoded by the shared reader thread, filtering to
+/// `min_level` and up. Runs forever if `follow` is set, otherwise captures
+/// for `SNAPSHOT_WINDOW` and returns. Lines are appended to `save_path` as
+/// they're printed, if given.
+pub fn run(records: Receiver<LogRecord>, min_level: Level, follow: bool, save_path: Option<String>) {
+    let mut save_file: Option<File> = save_path.map(|path| {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .expect("failed to open --save log file")
+    });
+
+    let deadline = Instant::now() + SNAPSHOT_WINDOW;
+
+    loop {
+        let record = if follow {
+            match records.recv() {
+                Ok(record) => record,
+                Err(_) => return,
+            }
+        } else {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining == Duration::ZERO {
+                return;
+            }
+            match records.recv_timeout(remaining) {
+                Ok(record) => record,
+                Err(_) => return,
+            }
+        };
+
+        if record.level >= min_level {
+            let line = render(&record);
+            println!("{line}");
+            if let Some(file) = save_file.as_mut() {
+                writeln!(file, "{line}").ok();
+            }
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use std::fs::OpenOptions;
-use std::io::{Read, Write, stdout};
+use std::io::{Write, stdout};
 use std::os::unix::fs::MetadataExt;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, mpsc};
 use std::thread::{self, sleep};
 use std::time;
 
@@ -11,6 +11,14 @@ use elf::ElfBytes;
 use elf::endian::AnyEndian;
 use serialport::SerialPort;
 
+mod bridge;
+mod dispatch;
+mod framing;
+mod logviewer;
+mod protocol;
+mod rpc;
+mod tasktable;
+
 const LOAD_PROG_MAGIC: &[u8] = "LOADPROG".as_bytes();
 const KILL_PROG_MAGIC: &[u8] = "KILLTASK".as_bytes();
 const LIST_TASKS_MAGIC: &[u8] = "LISTPROG".as_bytes();
@@ -30,6 +38,93 @@ struct Args {
     /// Baudrate for UART
     #[arg(short, long, default_value_t = 115200)]
     baudrate: u32,
+
+    /// Parity bit for the UART framing
+    #[arg(long, value_enum, default_value = "none")]
+    parity: ParityArg,
+    /// Number of stop bits
+    #[arg(long, value_enum, default_value = "1")]
+    stop_bits: StopBitsArg,
+    /// Number of data bits
+    #[arg(long, value_enum, default_value = "8")]
+    data_bits: DataBitsArg,
+    /// Flow control scheme
+    #[arg(long, value_enum, default_value = "none")]
+    flow_control: FlowControlArg,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum ParityArg {
+    None,
+    Even,
+    Odd,
+}
+
+impl From<ParityArg> for serialport::Parity {
+    fn from(value: ParityArg) -> Self {
+        match value {
+            ParityArg::None => serialport::Parity::None,
+            ParityArg::Even => serialport::Parity::Even,
+            ParityArg::Odd => serialport::Parity::Odd,
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum StopBitsArg {
+    #[value(name = "1")]
+    One,
+    #[value(name = "2")]
+    Two,
+}
+
+impl From<StopBitsArg> for serialport::StopBits {
+    fn from(value: StopBitsArg) -> Self {
+        match value {
+            StopBitsArg::One => serialport::StopBits::One,
+            StopBitsArg::Two => serialport::StopBits::Two,
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum DataBitsArg {
+    #[value(name = "5")]
+    Five,
+    #[value(name = "6")]
+    Six,
+    #[value(name = "7")]
+    Seven,
+    #[value(name = "8")]
+    Eight,
+}
+
+impl From<DataBitsArg> for serialport::DataBits {
+    fn from(value: DataBitsArg) -> Self {
+        match value {
+            DataBitsArg::Five => serialport::DataBits::Five,
+            DataBitsArg::Six => serialport::DataBits::Six,
+            DataBitsArg::Seven => serialport::DataBits::Seven,
+            DataBitsArg::Eight => serialport::DataBits::Eight,
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum FlowControlArg {
+    None,
+    Hardware,
+    Software,
+}
+
+impl From<FlowControlArg> for serialport::FlowControl {
+    fn from(value: FlowControlArg) -> Self {
+        match value {
+            FlowControlArg::None => serialport::FlowControl::None,
+            FlowControlArg::Hardware => serialport::FlowControl::Hardware,
+            FlowControlArg::Software => serialport::FlowControl::Software,
+        }
+    }
 }
 
 #[derive(Subcommand, Debug, Clone)]
@@ -53,12 +148,33 @@ enum Commands {
         identifier: String,
     },
     /// List all tasks and status on the Pico
-    List,
+    List {
+        /// Emit the task table as JSON instead of an aligned table
+        #[arg(long)]
+        json: bool,
+    },
     /// Serial logger
-    Log,
+    Log {
+        /// Suppress records below this level
+        #[arg(long, value_enum, default_value = "info")]
+        level: logviewer::LevelArg,
+        /// Keep streaming instead of capturing a short snapshot and exiting
+        #[arg(long)]
+        follow: bool,
+        /// Append rendered log lines to this file as they arrive
+        #[arg(long)]
+        save: Option<String>,
+    },
+    /// Bridge a Pico attached over serial onto the network so Load/Kill/
+    /// Relaunch/List can be driven by a remote client
+    Serve {
+        /// Address to accept bridge clients on, e.g. 0.0.0.0:4242
+        #[arg(long)]
+        bind: std::net::SocketAddr,
+    },
 }
 
-fn handle_load_cmd(file: String, symbol: String, identifier: String) -> Option<Vec<u8>> {
+pub(crate) fn handle_load_cmd(file: String, symbol: String, identifier: String) -> Option<Vec<u8>> {
     let path = std::path::PathBuf::from(file.clone());
     let file_data = std::fs::read(path.clone()).expect("failed to read the elf file");
     let slice = file_data.as_slice();
@@ -112,7 +228,7 @@ fn handle_load_cmd(file: String, symbol: String, identifier: String) -> Option<V
     Some(data)
 }
 
-fn handle_kill_cmd(identifier: String) -> Option<Vec<u8>> {
+pub(crate) fn handle_kill_cmd(identifier: String) -> Option<Vec<u8>> {
     let mut data: Vec<u8> = Vec::new();
 
     let ident_bytes = format!("{identifier:8}")
@@ -133,7 +249,7 @@ fn handle_kill_cmd(identifier: String) -> Option<Vec<u8>> {
     Some(data)
 }
 
-fn handle_relaunch_cmd(identifier: String) -> Option<Vec<u8>> {
+pub(crate) fn handle_relaunch_cmd(identifier: String) -> Option<Vec<u8>> {
     let mut data: Vec<u8> = Vec::new();
 
     let ident_bytes = format!("{identifier:8}")
@@ -154,7 +270,7 @@ fn handle_relaunch_cmd(identifier: String) -> Option<Vec<u8>> {
     Some(data)
 }
 
-fn handle_list_cmd() -> Option<Vec<u8>> {
+pub(crate) fn handle_list_cmd() -> Option<Vec<u8>> {
     let mut data: Vec<u8> = Vec::new();
 
     data.extend(LIST_TASKS_MAGIC);
@@ -163,10 +279,10 @@ fn handle_list_cmd() -> Option<Vec<u8>> {
 }
 
 // #[allow(unused_assignments)]
-fn handle_command(
-    cmd: Commands,
-    serial: Arc<Mutex<Box<dyn SerialPort + 'static>>>,
-) -> Option<Vec<u8>> {
+/// Build and send the wire payload for `cmd`. Returns the request id the
+/// command was tagged with, so the caller can wait for its reply; `None`
+/// for commands (like `Log`) that don't expect one.
+fn handle_command(cmd: Commands, serial: Arc<Mutex<Box<dyn SerialPort + 'static>>>) -> Option<u8> {
     let dump_path = std::path::PathBuf::from("/tmp/elf.dump");
     let mut dump_file = OpenOptions::new()
         .write(true)
@@ -183,14 +299,12 @@ fn handle_command(
             symbol,
             identifier,
         } => result = handle_load_cmd(file, symbol, identifier)?,
-        Commands::List => result = handle_list_cmd()?,
+        Commands::List { .. } => result = handle_list_cmd()?,
 
         Commands::Relaunch { identifier } => result = handle_relaunch_cmd(identifier)?,
         Commands::Kill { identifier } => result = handle_kill_cmd(identifier)?,
-        Commands::Log => {
-            drop(result);
-            return None;
-        }
+        Commands::Log { .. } => return None,
+        Commands::Serve { .. } => return None,
     }
 
     println!(
@@ -202,14 +316,19 @@ fn handle_command(
         .write_all(&result)
         .expect("failed to write data to the dumpfile");
 
-    let mut writer_handle = serial.lock().unwrap();
-    for byte in result {
-        writer_handle.write_all(&[byte]).unwrap();
-        writer_handle.flush().unwrap();
-    }
+    let (request_id, tagged) = protocol::tag_with_request_id(&result);
+    let framed = framing::frame(&tagged);
+    println!(
+        "[PICOSH] framed {} byte payload (request id {}) into {} bytes (cobs + crc16)",
+        result.len(),
+        request_id,
+        framed.len()
+    );
 
+    let mut writer_handle = serial.lock().unwrap();
+    writer_handle.write_all(&framed).unwrap();
     writer_handle.flush().unwrap();
-    None
+    Some(request_id)
 }
 
 fn main() {
@@ -217,28 +336,95 @@ fn main() {
 
     let serial = Arc::new(Mutex::new(
         serialport::new(args.device.clone(), args.baudrate)
+            .parity(args.parity.into())
+            .stop_bits(args.stop_bits.into())
+            .data_bits(args.data_bits.into())
+            .flow_control(args.flow_control.into())
             .open()
             .unwrap_or_else(|_| panic!("unable to open device {}", args.device.clone())),
     ));
 
-    let reader_serial = Arc::clone(&serial);
-    // let write_serial = Arc::clone(&serial);
+    let (reply_tx, reply_rx) = mpsc::channel::<protocol::Reply>();
+    let (table_tx, table_rx) = mpsc::channel::<(u8, Vec<tasktable::TaskInfo>)>();
+    let (log_tx, log_rx) = mpsc::channel::<logviewer::LogRecord>();
+    let (passthrough_tx, passthrough_rx) = mpsc::channel::<Vec<u8>>();
+
+    dispatch::spawn_reader(
+        Arc::clone(&serial),
+        rpc::Registry::with_builtins(),
+        dispatch::Channels {
+            replies: reply_tx,
+            tables: table_tx,
+            logs: log_tx,
+            passthrough: passthrough_tx,
+        },
+    );
+
+    if let Commands::Serve { bind } = args.cmd.clone() {
+        bridge::serve(bind, serial, reply_rx, table_rx, log_rx, passthrough_rx)
+            .expect("bridge server failed");
+        return;
+    }
+
+    if let Commands::Log {
+        level,
+        follow,
+        save,
+    } = args.cmd.clone()
+    {
+        drop(reply_rx);
+        drop(table_rx);
+        drop(passthrough_rx);
+        logviewer::run(log_rx, level.into(), follow, save);
+        return;
+    }
+
+    drop(log_rx);
 
+    // Frames the shared reader doesn't recognise as an RPC call, task
+    // table, reply, or log record (plain print text from the firmware) are
+    // just mirrored to stdout, same as before there was a shared reader
+    // thread. Log records are discarded here; only `Log` and `Serve` show them.
     thread::spawn(move || {
-        loop {
-            let handler_result = reader_serial.lock();
-
-            if let Ok(mut handler) = handler_result {
-                let mut read_buf: Vec<u8> = vec![0; 1];
-                _ = handler.read_exact(read_buf.as_mut_slice());
-                print!("{}", String::from_utf8_lossy(&read_buf));
-                stdout().flush().unwrap();
-            }
+        for payload in passthrough_rx {
+            print!("{}", String::from_utf8_lossy(&payload));
+            stdout().flush().unwrap();
         }
     });
 
+    let list_json = matches!(&args.cmd, Commands::List { json } if *json);
+    let cmd_is_list = matches!(&args.cmd, Commands::List { .. });
+
     sleep(time::Duration::from_secs(2));
-    _ = handle_command(args.cmd, Arc::clone(&serial));
+    let request_id = handle_command(args.cmd, Arc::clone(&serial));
+
+    if let Some(request_id) = request_id {
+        if cmd_is_list {
+            match tasktable::await_task_table(&table_rx, request_id, time::Duration::from_secs(5))
+            {
+                Some(tasks) if list_json => print!("{}", tasktable::render_json(&tasks)),
+                Some(tasks) => print!("{}", tasktable::render_table(&tasks)),
+                None => {
+                    eprintln!("[PICOSH] timed out waiting for the task table");
+                    std::process::exit(1);
+                }
+            }
+        } else {
+            match protocol::send_and_await(&reply_rx, request_id, time::Duration::from_secs(5)) {
+                Some(reply) if reply.status.is_ok() => {
+                    println!("[PICOSH] ok: {}", reply.message);
+                }
+                Some(reply) => {
+                    eprintln!("[PICOSH] error: {} ({})", reply.status, reply.message);
+                    std::process::exit(1);
+                }
+                None => {
+                    eprintln!("[PICOSH] timed out waiting for a reply to request {request_id}");
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
 
     loop {
         thread::sleep(time::Duration::from_secs(1));
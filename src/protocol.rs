@@ -0,0 +1,119 @@
+//! Request/response protocol layered on top of the COBS+CRC framing.
+//!
+//! Every outgoing command carries a one-byte request id so its reply can be
+//! matched up on the way back, instead of picosh just hoping whatever comes
+//! back next belongs to the command it sent.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// Marks a reply frame, the same way `RPCCALL;`/`RPCRESU;` (`rpc.rs`) and
+/// `LOGLINE;` (`logviewer.rs`) mark theirs, so a reply can't be mistaken for
+/// passthrough (or vice versa) on a 2-byte heuristic alone.
+pub const REPLY_MAGIC: &[u8] = b"REPLY00;";
+
+static NEXT_REQUEST_ID: AtomicU8 = AtomicU8::new(0);
+
+/// Allocate the next request id, wrapping at 256.
+pub fn next_request_id() -> u8 {
+    NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Status the Pico reports for a completed command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    SymbolNotFound,
+    OutOfMemory,
+    NoSuchTask,
+}
+
+impl Status {
+    fn from_byte(byte: u8) -> Option<Status> {
+        match byte {
+            0x00 => Some(Status::Ok),
+            0x01 => Some(Status::SymbolNotFound),
+            0x02 => Some(Status::OutOfMemory),
+            0x03 => Some(Status::NoSuchTask),
+            _ => None,
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        matches!(self, Status::Ok)
+    }
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self {
+            Status::Ok => "ok",
+            Status::SymbolNotFound => "symbol not found",
+            Status::OutOfMemory => "out of memory",
+            Status::NoSuchTask => "no such task",
+        };
+        write!(f, "{label}")
+    }
+}
+
+/// A decoded reply frame: the request id it answers, its status, and an
+/// optional human-readable message.
+#[derive(Debug, Clone)]
+pub struct Reply {
+    pub request_id: u8,
+    pub status: Status,
+    pub message: String,
+}
+
+impl Reply {
+    /// Try to parse `payload` as `[REPLY_MAGIC][request_id][status]
+    /// [message...]`. Returns `None` if it doesn't start with `REPLY_MAGIC`
+    /// or the status byte isn't a recognised code, so plain (unframed
+    /// protocol) byte streams like the `Log` output fall through untouched.
+    pub fn decode(payload: &[u8]) -> Option<Reply> {
+        let rest = payload.strip_prefix(REPLY_MAGIC)?;
+        if rest.len() < 2 {
+            return None;
+        }
+        let request_id = rest[0];
+        let status = Status::from_byte(rest[1])?;
+        let message = String::from_utf8_lossy(&rest[2..]).into_owned();
+        Some(Reply {
+            request_id,
+            status,
+            message,
+        })
+    }
+}
+
+/// Prepend a freshly allocated request id to `payload`, returning the id
+/// alongside the tagged (not yet framed) command so the caller can wait on
+/// the matching reply.
+pub fn tag_with_request_id(payload: &[u8]) -> (u8, Vec<u8>) {
+    let request_id = next_request_id();
+    let mut tagged = Vec::with_capacity(payload.len() + 1);
+    tagged.push(request_id);
+    tagged.extend_from_slice(payload);
+    (request_id, tagged)
+}
+
+/// Block on `replies` until a `Reply` matching `request_id` arrives, or
+/// until `timeout` elapses.
+pub fn send_and_await(replies: &Receiver<Reply>, request_id: u8, timeout: Duration) -> Option<Reply> {
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return None;
+        }
+
+        match replies.recv_timeout(remaining) {
+            Ok(reply) if reply.request_id == request_id => return Some(reply),
+            Ok(_) => continue,
+            Err(RecvTimeoutError::Timeout) => return None,
+            Err(RecvTimeoutError::Disconnected) => return None,
+        }
+    }
+}
@@ -0,0 +1,264 @@
+//! Host-side RPC server, modelled on ARTIQ's `rpc_send`/`rpc_recv`: a
+//! program loaded onto the Pico can call back into picosh over the same
+//! UART instead of only ever being called into.
+//!
+//! An RPC call frame is `[RPC_CALL_MAGIC][method_id: u8][arg_count: u8]`
+//! followed by `arg_count` length-prefixed argument slices, each
+//! `[len: u16 LE][bytes]`. picosh dispatches the call to a registered
+//! handler and writes the result back as `[RPC_RESULT_MAGIC][method_id][bytes]`.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serialport::SerialPort;
+
+use crate::framing;
+
+pub const RPC_CALL_MAGIC: &[u8] = b"RPCCALL;";
+pub const RPC_RESULT_MAGIC: &[u8] = b"RPCRESU;";
+
+/// Returns the host's current unix time in milliseconds.
+pub const METHOD_CLOCK: u8 = 0x01;
+/// Writes its single argument to host stdout.
+pub const METHOD_STDOUT_WRITE: u8 = 0x02;
+/// Reads the file named by its single argument, resolved under
+/// `FILE_READ_ROOT`, and returns its bytes (empty if it can't be read or
+/// lies outside that root).
+pub const METHOD_FILE_READ: u8 = 0x03;
+
+/// A single decoded RPC call from the Pico: a method id and its argument
+/// slices.
+#[derive(Debug, Clone)]
+pub struct RpcCall {
+    pub method_id: u8,
+    pub args: Vec<Vec<u8>>,
+}
+
+impl RpcCall {
+    /// Parse `payload` as an RPC call frame. Returns `None` if it doesn't
+    /// start with `RPC_CALL_MAGIC` or is malformed.
+    pub fn decode(payload: &[u8]) -> Option<RpcCall> {
+        let rest = payload.strip_prefix(RPC_CALL_MAGIC)?;
+        let method_id = *rest.first()?;
+        let arg_count = *rest.get(1)? as usize;
+        let mut cursor = &rest[2..];
+
+        let mut args = Vec::with_capacity(arg_count);
+        for _ in 0..arg_count {
+            if cursor.len() < 2 {
+                return None;
+            }
+            let len = u16::from_le_bytes([cursor[0], cursor[1]]) as usize;
+            cursor = &cursor[2..];
+            if cursor.len() < len {
+                return None;
+            }
+            args.push(cursor[..len].to_vec());
+            cursor = &cursor[len..];
+        }
+
+        Some(RpcCall { method_id, args })
+    }
+}
+
+/// A Rust-side RPC handler: takes the call's argument slices and returns the
+/// bytes to send back as its result.
+pub type Handler = Box<dyn Fn(&[Vec<u8>]) -> Vec<u8> + Send + Sync>;
+
+/// Registry of method id -> handler, consulted whenever an `RpcCall` frame
+/// arrives from the Pico.
+pub struct Registry {
+    handlers: HashMap<u8, Handler>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Registry {
+    pub fn new() -> Registry {
+        Registry {
+            handlers: HashMap::new(),
+        }
+    }
+
+    pub fn register(&mut self, method_id: u8, handler: Handler) {
+        self.handlers.insert(method_id, handler);
+    }
+
+    pub fn dispatch(&self, call: &RpcCall) -> Vec<u8> {
+        match self.handlers.get(&call.method_id) {
+            Some(handler) => handler(&call.args),
+            None => {
+                eprintln!(
+                    "[PICOSH] no RPC handler registered for method {}",
+                    call.method_id
+                );
+                Vec::new()
+            }
+        }
+    }
+
+    /// The `clock`, `stdout write`, and `file read` handlers picosh ships
+    /// with out of the box.
+    pub fn with_builtins() -> Registry {
+        let mut registry = Registry::new();
+
+        registry.register(
+            METHOD_CLOCK,
+            Box::new(|_args| {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default();
+                (now.as_millis() as u64).to_le_bytes().to_vec()
+            }),
+        );
+
+        registry.register(
+            METHOD_STDOUT_WRITE,
+            Box::new(|args| {
+                if let Some(bytes) = args.first() {
+                    print!("{}", String::from_utf8_lossy(bytes));
+                    std::io::stdout().flush().ok();
+                }
+                Vec::new()
+            }),
+        );
+
+        registry.register(
+            METHOD_FILE_READ,
+            Box::new(|args| {
+                let requested = args
+                    .first()
+                    .map(|bytes| String::from_utf8_lossy(bytes).into_owned())
+                    .unwrap_or_default();
+                match resolve_readable_path(&requested) {
+                    Some(path) => std::fs::read(path).unwrap_or_default(),
+                    None => {
+                        eprintln!(
+                            "[PICOSH] rejected file read RPC outside {}: {requested}",
+                            FILE_READ_ROOT
+                        );
+                        Vec::new()
+                    }
+                }
+            }),
+        );
+
+        registry
+    }
+}
+
+/// Directory loaded programs are allowed to read files from via
+/// `METHOD_FILE_READ`. Nothing outside this tree (SSH keys, env files, the
+/// rest of the host) is reachable from the Pico.
+const FILE_READ_ROOT: &str = "/var/lib/picosh/rpc-files";
+
+/// Resolve `requested` against `FILE_READ_ROOT`, rejecting it unless it
+/// canonicalizes to a path still inside that root (so `../` traversal,
+/// symlinks out of the root, and absolute paths elsewhere are all refused).
+fn resolve_readable_path(requested: &str) -> Option<std::path::PathBuf> {
+    resolve_readable_path_under(FILE_READ_ROOT, requested)
+}
+
+/// Same as [`resolve_readable_path`], but against an arbitrary `root`
+/// instead of the hardcoded [`FILE_READ_ROOT`], so tests can exercise it
+/// against a throwaway directory.
+fn resolve_readable_path_under(root: &str, requested: &str) -> Option<std::path::PathBuf> {
+    let root = std::fs::canonicalize(root).ok()?;
+    let candidate = root.join(requested.trim_start_matches('/'));
+    let resolved = std::fs::canonicalize(&candidate).ok()?;
+    resolved.starts_with(&root).then_some(resolved)
+}
+
+/// Encode an RPC result payload ready to be passed to `framing::frame`.
+pub fn encode_result(method_id: u8, result: &[u8]) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(RPC_RESULT_MAGIC.len() + 1 + result.len());
+    payload.extend_from_slice(RPC_RESULT_MAGIC);
+    payload.push(method_id);
+    payload.extend_from_slice(result);
+    payload
+}
+
+/// Dispatch `call` against `registry` and write the framed result back to
+/// `serial`.
+pub fn handle_call(
+    call: &RpcCall,
+    registry: &Registry,
+    serial: &Arc<Mutex<Box<dyn SerialPort + 'static>>>,
+) {
+    let result = registry.dispatch(call);
+    let framed = framing::frame(&encode_result(call.method_id, &result));
+
+    let mut writer = serial.lock().unwrap();
+    writer.write_all(&framed).unwrap();
+    writer.flush().unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A throwaway root directory with `nested/inside.txt` inside it and
+    /// `outside.txt` as a sibling, for exercising `resolve_readable_path_under`
+    /// without touching the real `FILE_READ_ROOT`. Removed on drop.
+    struct TestRoot {
+        dir: std::path::PathBuf,
+        outside_file: std::path::PathBuf,
+    }
+
+    impl TestRoot {
+        fn new(name: &str) -> TestRoot {
+            let base = std::env::temp_dir().join(format!("picosh-rpc-test-{name}"));
+            let _ = std::fs::remove_dir_all(&base);
+            let dir = base.join("root");
+            std::fs::create_dir_all(dir.join("nested")).unwrap();
+            std::fs::write(dir.join("nested/inside.txt"), b"inside").unwrap();
+
+            let outside_file = base.join("outside.txt");
+            std::fs::write(&outside_file, b"outside").unwrap();
+
+            TestRoot { dir, outside_file }
+        }
+
+        fn root(&self) -> &str {
+            self.dir.to_str().unwrap()
+        }
+    }
+
+    impl Drop for TestRoot {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(self.dir.parent().unwrap());
+        }
+    }
+
+    #[test]
+    fn accepts_legitimate_nested_path() {
+        let root = TestRoot::new("accepts-nested");
+        let resolved = resolve_readable_path_under(root.root(), "nested/inside.txt").unwrap();
+        assert_eq!(std::fs::read(resolved).unwrap(), b"inside");
+    }
+
+    #[test]
+    fn rejects_dot_dot_traversal() {
+        let root = TestRoot::new("rejects-dotdot");
+        assert!(resolve_readable_path_under(root.root(), "../outside.txt").is_none());
+    }
+
+    #[test]
+    fn rejects_absolute_path_elsewhere() {
+        let root = TestRoot::new("rejects-absolute");
+        assert!(resolve_readable_path_under(root.root(), "/etc/passwd").is_none());
+    }
+
+    #[test]
+    fn rejects_symlink_pointing_outside_root() {
+        let root = TestRoot::new("rejects-symlink");
+        std::os::unix::fs::symlink(&root.outside_file, root.dir.join("escape.txt")).unwrap();
+        assert!(resolve_readable_path_under(root.root(), "escape.txt").is_none());
+    }
+}
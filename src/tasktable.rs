@@ -0,0 +1,175 @@
+//! Parsing and rendering for the `List` command's task-table reply.
+//!
+//! The Pico replies to `LISTPROG` with `[request_id][count: u16 LE]`
+//! followed by `count` fixed-width entries of:
+//! `[identifier: 8 bytes][entry: u64 LE][load size: u64 LE][state: u8][ticks: u64 LE]`.
+
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+const ENTRY_LEN: usize = 8 + 8 + 8 + 1 + 8;
+
+/// Run-state byte reported alongside each task entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunState {
+    Running,
+    Ready,
+    Blocked,
+    Suspended,
+    Terminated,
+    Unknown(u8),
+}
+
+impl RunState {
+    fn from_byte(byte: u8) -> RunState {
+        match byte {
+            0 => RunState::Running,
+            1 => RunState::Ready,
+            2 => RunState::Blocked,
+            3 => RunState::Suspended,
+            4 => RunState::Terminated,
+            other => RunState::Unknown(other),
+        }
+    }
+
+    fn as_str(&self) -> String {
+        match self {
+            RunState::Running => "running".to_string(),
+            RunState::Ready => "ready".to_string(),
+            RunState::Blocked => "blocked".to_string(),
+            RunState::Suspended => "suspended".to_string(),
+            RunState::Terminated => "terminated".to_string(),
+            RunState::Unknown(byte) => format!("unknown({byte})"),
+        }
+    }
+}
+
+/// One row of the task table.
+#[derive(Debug, Clone)]
+pub struct TaskInfo {
+    pub identifier: String,
+    pub entry: u64,
+    pub size: u64,
+    pub state: RunState,
+    pub ticks: u64,
+}
+
+/// Try to parse `payload` as a task-table reply. Requires the length to
+/// match `count` exactly, so a generic status reply or raw log text can't
+/// be mistaken for a table.
+pub fn decode(payload: &[u8]) -> Option<(u8, Vec<TaskInfo>)> {
+    if payload.len() < 3 {
+        return None;
+    }
+
+    let request_id = payload[0];
+    let count = u16::from_le_bytes([payload[1], payload[2]]) as usize;
+
+    if payload.len() != 3 + count * ENTRY_LEN {
+        return None;
+    }
+
+    let mut tasks = Vec::with_capacity(count);
+    let mut cursor = &payload[3..];
+
+    for _ in 0..count {
+        let identifier = String::from_utf8_lossy(&cursor[0..8]).trim_end().to_string();
+        let entry = u64::from_le_bytes(cursor[8..16].try_into().unwrap());
+        let size = u64::from_le_bytes(cursor[16..24].try_into().unwrap());
+        let state = RunState::from_byte(cursor[24]);
+        let ticks = u64::from_le_bytes(cursor[25..33].try_into().unwrap());
+
+        tasks.push(TaskInfo {
+            identifier,
+            entry,
+            size,
+            state,
+            ticks,
+        });
+
+        cursor = &cursor[ENTRY_LEN..];
+    }
+
+    Some((request_id, tasks))
+}
+
+/// Block on `tables` until a task table tagged with `request_id` arrives,
+/// or until `timeout` elapses.
+pub fn await_task_table(
+    tables: &Receiver<(u8, Vec<TaskInfo>)>,
+    request_id: u8,
+    timeout: Duration,
+) -> Option<Vec<TaskInfo>> {
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return None;
+        }
+
+        match tables.recv_timeout(remaining) {
+            Ok((id, tasks)) if id == request_id => return Some(tasks),
+            Ok(_) => continue,
+            Err(RecvTimeoutError::Timeout) => return None,
+            Err(RecvTimeoutError::Disconnected) => return None,
+        }
+    }
+}
+
+/// Render an aligned `ID  STATE  ENTRY  SIZE  TICKS` table.
+pub fn render_table(tasks: &[TaskInfo]) -> String {
+    let mut out = String::new();
+    out.push_str(&format!(
+        "{:<8}  {:<10}  {:<10}  {:>10}  {:>10}\n",
+        "ID", "STATE", "ENTRY", "SIZE", "TICKS"
+    ));
+    for task in tasks {
+        out.push_str(&format!(
+            "{:<8}  {:<10}  0x{:<8x}  {:>10}  {:>10}\n",
+            task.identifier,
+            task.state.as_str(),
+            task.entry,
+            task.size,
+            task.ticks
+        ));
+    }
+    out
+}
+
+/// Escape `s` for embedding in a JSON string literal: quotes, backslashes,
+/// and control characters all need escaping, since `identifier` comes
+/// straight off the wire and isn't guaranteed to be printable.
+fn json_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Render the same rows as a JSON array.
+pub fn render_json(tasks: &[TaskInfo]) -> String {
+    let rows: Vec<String> = tasks
+        .iter()
+        .map(|task| {
+            format!(
+                "{{\"id\":\"{}\",\"state\":\"{}\",\"entry\":{},\"size\":{},\"ticks\":{}}}",
+                json_escape(&task.identifier),
+                task.state.as_str(),
+                task.entry,
+                task.size,
+                task.ticks
+            )
+        })
+        .collect();
+    format!("[{}]", rows.join(","))
+}